@@ -1,18 +1,44 @@
+use super::content_type::{self, ContentType};
+use super::unixfs_pb;
 use crate::{Error, Ipfs, IpfsTypes};
 use async_stream::stream;
-use futures::stream::Stream;
+use futures::stream::{FuturesOrdered, Stream, StreamExt};
 use ipfs_unixfs::file::{visit::IdleFileVisit, FileReadFailed};
 use libipld::cid::Cid;
 use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::ops::Range;
+use std::pin::Pin;
+
+/// Options for [`cat_with`].
+#[derive(Debug, Clone)]
+pub struct CatOptions {
+    /// Byte range within the file to read; `None` reads the whole file.
+    pub range: Option<Range<u64>>,
+    /// Number of blocks to keep outstanding (fetched or being fetched),
+    /// counting the block the traversal currently needs. `1` matches
+    /// [`cat`]'s strictly serial behaviour.
+    pub prefetch: usize,
+}
+
+impl Default for CatOptions {
+    fn default() -> Self {
+        CatOptions {
+            range: None,
+            prefetch: 1,
+        }
+    }
+}
 
 /// IPFS cat operation, producing a stream of file bytes. This is generic over the different kinds
 /// of ways to own an `Ipfs` value in order to support both operating with borrowed `Ipfs` value
 /// and an owned value. Passing an owned value allows the return value to be `'static`, which can
 /// be helpful in some contexts, like the http.
 ///
-/// Returns a stream of bytes on the file pointed with the Cid.
+/// Returns a stream of bytes on the file pointed with the Cid. Equivalent to
+/// `cat_with(ipfs, cid, CatOptions { range, prefetch: 1 })`, i.e. blocks are
+/// fetched one at a time as the traversal needs them.
 pub fn cat<'a, Types, MaybeOwned>(
     ipfs: MaybeOwned,
     cid: Cid,
@@ -22,26 +48,70 @@ where
     Types: IpfsTypes,
     MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
 {
-    use bitswap::Block;
+    cat_with(ipfs, cid, CatOptions { range, prefetch: 1 })
+}
 
+/// Like [`cat`], but keeps up to `options.prefetch` `get_block` requests
+/// outstanding ahead of where the traversal currently is, pipelining fetches
+/// instead of fetching one block, walking it, then fetching the next.
+///
+/// Blocks that resolve out of order are held in a small reorder buffer keyed
+/// by Cid so that output byte ordering still matches the traversal order.
+pub fn cat_with<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    cid: Cid,
+    options: CatOptions,
+) -> impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
     // using async_stream here at least to get on faster; writing custom streams is not too easy
     // but this might be easy enough to write open.
     stream! {
-        let mut visit = IdleFileVisit::default();
-        if let Some(range) = range {
-            visit = visit.with_target_range(range);
-        }
-
         // Get the root block to start the traversal. The stream does not expose any of the file
         // metadata. To get to it the user needs to create a Visitor over the first block.
         let borrow = ipfs.borrow();
-        let Block { cid, data } = match borrow.get_block(&cid).await {
+        let root = match borrow.get_block(&cid).await {
             Ok(block) => block,
             Err(e) => {
                 yield Err(TraversalFailed::Loading(cid, e));
                 return;
             }
         };
+        drop(borrow);
+
+        let mut inner = Box::pin(cat_with_block(ipfs, root, options));
+        while let Some(item) = inner.next().await {
+            yield item;
+        }
+    }
+}
+
+/// The body of [`cat_with`], parameterized over an already-fetched `root`
+/// block so that callers which needed to fetch and inspect the root for
+/// their own purposes (e.g. [`cat_with_metadata`]) don't have to fetch it a
+/// second time just to hand it to this function.
+fn cat_with_block<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    root: bitswap::Block,
+    options: CatOptions,
+) -> impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
+    use bitswap::Block;
+
+    let prefetch = options.prefetch.max(1);
+
+    stream! {
+        let mut visit = IdleFileVisit::default();
+        if let Some(range) = options.range {
+            visit = visit.with_target_range(range);
+        }
+
+        let Block { cid, data } = root;
 
         // Start the visit from the root block.
         let mut visit = match visit.start(&data) {
@@ -61,17 +131,45 @@ where
             }
         };
 
+        // Blocks that have finished fetching but are still ahead of where the
+        // traversal needs them, keyed by Cid so they can be matched up
+        // regardless of the order their fetches complete in.
+        let mut fetched: HashMap<Cid, Block> = HashMap::new();
+        let mut requested: HashSet<Cid> = HashSet::new();
+        let mut inflight = FuturesOrdered::new();
+
         loop {
-            // TODO: if it was possible, it would make sense to start downloading N of these
-            let (next, _) = visit.pending_links();
+            // Top up the prefetch window with `next` and however many of the
+            // links that will be needed after it fit within `prefetch`.
+            let (next, following) = visit.pending_links();
+            let next = *next;
+            let window = std::iter::once(next).chain(following.copied()).take(prefetch);
 
-            let borrow = ipfs.borrow();
-            let Block { cid, data } = match borrow.get_block(&next).await {
-                Ok(block) => block,
-                Err(e) => {
-                    yield Err(TraversalFailed::Loading(next.to_owned(), e));
-                    return;
-                },
+            for wanted in window {
+                if requested.insert(wanted) {
+                    let borrow = ipfs.borrow();
+                    inflight.push_back(async move { (wanted, borrow.get_block(&wanted).await) });
+                }
+            }
+
+            let data = loop {
+                if let Some(block) = fetched.remove(&next) {
+                    // The same Cid can be linked more than once (deduplicated
+                    // chunks); forget it so a later occurrence is fetched again.
+                    requested.remove(&next);
+                    break block.data;
+                }
+
+                match inflight.next().await {
+                    Some((cid, Ok(block))) => {
+                        fetched.insert(cid, block);
+                    }
+                    Some((cid, Err(e))) => {
+                        yield Err(TraversalFailed::Loading(cid, e));
+                        return;
+                    }
+                    None => unreachable!("next is always added to the prefetch window above"),
+                }
             };
 
             match visit.continue_walk(&data) {
@@ -87,7 +185,7 @@ where
                     }
                 }
                 Err(e) => {
-                    yield Err(TraversalFailed::Walking(cid, e));
+                    yield Err(TraversalFailed::Walking(next, e));
                     return;
                 }
             }
@@ -95,6 +193,78 @@ where
     }
 }
 
+/// Metadata decoded from a file's root UnixFS node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub filesize: Option<u64>,
+    pub mode: Option<u32>,
+    /// `(seconds, fractional_nanoseconds)` since the Unix epoch.
+    pub mtime: Option<(i64, u32)>,
+}
+
+/// Like [`cat`], but also returns the root node's UnixFS metadata (total
+/// filesize, mode, mtime if present) and a best-effort content-type guess
+/// sniffed from the first chunk of *leaf* bytes, alongside the byte stream.
+///
+/// This lets an HTTP gateway set `Content-Type` without a separate pass over
+/// the file: the root metadata was already being read and discarded by
+/// [`cat`]'s initial `visit.start(&data)` call. For any file with more than
+/// one block the root node itself carries no inline `Data.Data` (`start()`
+/// yields an empty slice for it, same as [`cat`] relies on), so sniffing has
+/// to walk to and fetch the first leaf rather than sniffing the root's bytes.
+pub async fn cat_with_metadata<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    cid: Cid,
+    range: Option<Range<u64>>,
+) -> Result<
+    (
+        Metadata,
+        ContentType,
+        impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a,
+    ),
+    TraversalFailed,
+>
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
+    let borrow = ipfs.borrow();
+    let root = borrow
+        .get_block(&cid)
+        .await
+        .map_err(|e| TraversalFailed::Loading(cid, e))?;
+    drop(borrow);
+
+    let fsdata = unixfs_pb::parse_pb_node(&root.data)
+        .ok()
+        .and_then(|node| unixfs_pb::parse_unixfs_data(&node.data).ok())
+        .unwrap_or_default();
+
+    let metadata = Metadata {
+        filesize: fsdata.filesize,
+        mode: fsdata.mode,
+        mtime: fsdata.mtime,
+    };
+
+    // Drive the same stream `cat_with` would produce from this root block,
+    // reusing it instead of fetching and walking the root a second time.
+    // Its first yielded chunk is, by construction (bytes are only yielded
+    // when non-empty), the first leaf's bytes -- exactly what's needed to
+    // sniff.
+    let mut stream: Pin<Box<dyn Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'a>> =
+        Box::pin(cat_with_block(ipfs, root, CatOptions { range, prefetch: 1 }));
+
+    let first = stream.next().await;
+
+    let content_type = match &first {
+        Some(Ok(bytes)) => content_type::sniff(bytes),
+        // A read error or an empty file both leave nothing to sniff.
+        Some(Err(_)) | None => content_type::sniff(&[]),
+    };
+
+    Ok((metadata, content_type, futures::stream::iter(first).chain(stream)))
+}
+
 /// Types of failures which can occur while walking the UnixFS graph.
 #[derive(Debug)]
 pub enum TraversalFailed {
@@ -0,0 +1,137 @@
+//! Content-type sniffing over the first chunk of a file's bytes: a small
+//! magic-number table for common media, falling back to the standard
+//! "content inspector" binary-vs-text heuristic (a NUL byte, or a high
+//! ratio of control bytes, means binary; otherwise the buffer must decode
+//! as valid text).
+
+/// The result of sniffing the first bytes of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// A recognized magic number; value is a MIME type.
+    Known(&'static str),
+    /// No magic number matched, and the buffer looks like UTF-8 or UTF-16 text.
+    Text,
+    /// No magic number matched, and the buffer does not look like text.
+    Binary,
+}
+
+impl ContentType {
+    /// The MIME type to use in an HTTP `Content-Type` header.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ContentType::Known(mime) => mime,
+            ContentType::Text => "text/plain; charset=utf-8",
+            ContentType::Binary => "application/octet-stream",
+        }
+    }
+}
+
+/// `(magic bytes, MIME type)` table for formats commonly seen on IPFS.
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// Sniffs the content type of a file from its first chunk of bytes.
+/// `buf` should be the first leaf's worth of data (a few KiB is plenty);
+/// passing the whole file is unnecessary and wasteful.
+pub fn sniff(buf: &[u8]) -> ContentType {
+    for (magic, mime) in MAGIC_NUMBERS {
+        if buf.starts_with(magic) {
+            return ContentType::Known(mime);
+        }
+    }
+
+    if looks_binary(buf) {
+        ContentType::Binary
+    } else {
+        ContentType::Text
+    }
+}
+
+fn looks_binary(buf: &[u8]) -> bool {
+    if buf.is_empty() {
+        return false;
+    }
+
+    // UTF-16 with a BOM is valid text but is full of NUL bytes and will not
+    // decode as UTF-8, so this has to be checked before the NUL-byte check
+    // below, or all UTF-16 text would be misclassified as binary.
+    if buf.starts_with(&[0xff, 0xfe]) || buf.starts_with(&[0xfe, 0xff]) {
+        return false;
+    }
+
+    if buf.contains(&0) {
+        return true;
+    }
+
+    let control_bytes = buf
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r'))
+        .count();
+
+    // More than 10% non-whitespace control bytes: binary, mirroring the
+    // common "content inspector" heuristic.
+    if control_bytes * 10 > buf.len() {
+        return true;
+    }
+
+    match std::str::from_utf8(buf) {
+        Ok(_) => false,
+        // An error with no error_len() means the buffer merely ends mid-way
+        // through a multi-byte sequence, which happens whenever `buf` is a
+        // truncated prefix (e.g. the first leaf of a larger file) rather
+        // than actually-invalid UTF-8; don't call that binary.
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_known_magic_numbers() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\nrest"), ContentType::Known("image/png"));
+        assert_eq!(sniff(b"\xff\xd8\xffrest"), ContentType::Known("image/jpeg"));
+    }
+
+    #[test]
+    fn plain_ascii_text_is_text() {
+        assert_eq!(sniff(b"hello, world!\n"), ContentType::Text);
+    }
+
+    #[test]
+    fn nul_bytes_are_binary() {
+        assert_eq!(sniff(b"abc\0def"), ContentType::Binary);
+    }
+
+    #[test]
+    fn utf16_with_bom_is_text_despite_nul_bytes() {
+        // "hi" as UTF-16LE with a BOM: every other byte is NUL.
+        let utf16le_hi = [0xff, 0xfe, b'h', 0x00, b'i', 0x00];
+        assert_eq!(sniff(&utf16le_hi), ContentType::Text);
+    }
+
+    #[test]
+    fn truncated_multibyte_utf8_prefix_is_still_text() {
+        // "café" in UTF-8, cut off right after the 2-byte 'é' sequence's
+        // leading byte: a valid prefix of a larger file's first chunk, not
+        // actually-invalid UTF-8.
+        let truncated = "café".as_bytes();
+        let cut = "caf".len() + 1;
+        assert_eq!(sniff(&truncated[..cut]), ContentType::Text);
+    }
+
+    #[test]
+    fn genuinely_invalid_utf8_is_binary() {
+        // 0xC0 is never a valid UTF-8 lead byte (it could only encode a
+        // value below 0x80, which must be a single byte); this isn't a
+        // truncated sequence, it's outright invalid.
+        assert_eq!(sniff(&[0xC0, 0xAF]), ContentType::Binary);
+    }
+}
@@ -0,0 +1,541 @@
+//! A random-access file handle over a UnixFS DAG, implementing
+//! `tokio::io::{AsyncRead, AsyncSeek}` on top of the same block-by-block
+//! traversal [`cat`](super::cat::cat) uses.
+//!
+//! A UnixFS DAG has no offset index up front, so a seek restarts
+//! `IdleFileVisit::with_target_range(offset..)` from the root. To make
+//! repeated nearby seeks/reads cheap, every leaf visited along the way is
+//! cached as a `byte range -> block Cid` entry (together with the leaf's
+//! intra-block offset, recovered by comparing the visited slice against the
+//! leaf's full decoded payload), except a leaf clipped at both ends, whose
+//! start cannot be recovered that way; a read that lands inside an already-cached
+//! range re-fetches just that one block instead of walking from the root.
+
+use super::unixfs_pb;
+use crate::{Error, Ipfs, IpfsTypes};
+use ipfs_unixfs::file::{visit::IdleFileVisit, FileReadFailed};
+use libipld::cid::Cid;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+/// A byte range, fully backed by a single UnixFS leaf block.
+#[derive(Debug, Clone, Copy)]
+struct CachedLeaf {
+    end: u64,
+    cid: Cid,
+}
+
+/// The result of a single fetch: the requested bytes, every leaf discovered
+/// along the way (to be merged into the handle's range cache), and the
+/// file's total size, when a root node was visited.
+struct Fetched {
+    bytes: Vec<u8>,
+    leaves: Vec<(u64, CachedLeaf)>,
+    filesize: Option<u64>,
+}
+
+type FetchFuture = Pin<Box<dyn Future<Output = Result<Fetched, ReadFailed>> + Send>>;
+
+/// A positional, seekable handle onto a UnixFS file, suitable for serving
+/// HTTP range requests or media scrubbing.
+pub struct UnixFsFile<Types: IpfsTypes> {
+    ipfs: Ipfs<Types>,
+    root: Cid,
+    position: u64,
+    filesize: Option<u64>,
+    ranges: BTreeMap<u64, CachedLeaf>,
+    ready: VecDeque<u8>,
+    pending_read: Option<FetchFuture>,
+    pending_seek: Option<(i64, FetchFuture)>,
+}
+
+impl<Types: IpfsTypes> UnixFsFile<Types> {
+    /// Opens a handle onto the file at `root`, positioned at offset 0. The
+    /// Cid is not validated until the first read or seek to the end.
+    pub fn new(ipfs: Ipfs<Types>, root: Cid) -> Self {
+        UnixFsFile {
+            ipfs,
+            root,
+            position: 0,
+            filesize: None,
+            ranges: BTreeMap::new(),
+            ready: VecDeque::new(),
+            pending_read: None,
+            pending_seek: None,
+        }
+    }
+
+    /// Reads up to `len` bytes starting at `offset`, without disturbing the
+    /// handle's current seek position.
+    pub async fn pread(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, ReadFailed> {
+        let fetched = Self::fetch_at(self.ipfs.clone(), self.root, self.ranges.clone(), offset, len).await?;
+        self.merge(fetched.leaves, fetched.filesize);
+        Ok(fetched.bytes)
+    }
+
+    fn cached_leaf_at(ranges: &BTreeMap<u64, CachedLeaf>, position: u64) -> Option<(u64, CachedLeaf)> {
+        ranges
+            .range(..=position)
+            .next_back()
+            .filter(|(_, leaf)| position < leaf.end)
+            .map(|(start, leaf)| (*start, *leaf))
+    }
+
+    fn merge(&mut self, leaves: Vec<(u64, CachedLeaf)>, filesize: Option<u64>) {
+        if let Some(filesize) = filesize {
+            self.filesize = Some(filesize);
+        }
+        for (start, leaf) in leaves {
+            self.ranges.insert(start, leaf);
+        }
+    }
+
+    /// Looks up `position` in the already-discovered `ranges`, falling back
+    /// to a fresh root-to-leaf walk on a cache miss. Takes an owned `Ipfs`
+    /// handle and a snapshot of the range cache so the resulting future is
+    /// `'static` and can be stashed across `poll_read`/`poll_complete` calls.
+    async fn fetch_at(
+        ipfs: Ipfs<Types>,
+        root: Cid,
+        ranges: BTreeMap<u64, CachedLeaf>,
+        position: u64,
+        want: usize,
+    ) -> Result<Fetched, ReadFailed> {
+        if let Some((start, leaf)) = Self::cached_leaf_at(&ranges, position) {
+            Self::read_cached_leaf(ipfs, start, leaf, position, want).await
+        } else {
+            Self::walk_from_root(ipfs, root, position, want).await
+        }
+    }
+
+    /// Re-fetches an already-discovered leaf block directly, without
+    /// restarting the walk from the root.
+    async fn read_cached_leaf(
+        ipfs: Ipfs<Types>,
+        leaf_start: u64,
+        leaf: CachedLeaf,
+        position: u64,
+        want: usize,
+    ) -> Result<Fetched, ReadFailed> {
+        use bitswap::Block;
+
+        let Block { data, .. } = ipfs
+            .get_block(&leaf.cid)
+            .await
+            .map_err(|e| ReadFailed::Loading(leaf.cid, e))?;
+        let payload = leaf_payload(leaf.cid, &data)
+            .map_err(|e| ReadFailed::Decoding(leaf.cid, e.to_string()))?;
+
+        let start = (position - leaf_start) as usize;
+        let end = payload.len().min(start + want);
+        let bytes = payload.get(start..end).unwrap_or_default().to_vec();
+
+        Ok(Fetched {
+            bytes,
+            leaves: vec![(leaf_start, leaf)],
+            filesize: None,
+        })
+    }
+
+    /// Restarts `IdleFileVisit::with_target_range(position..)` from `root`,
+    /// walking forward only as far as needed to gather `want` bytes (or hit
+    /// EOF), recording every visited leaf's byte range along the way.
+    async fn walk_from_root(
+        ipfs: Ipfs<Types>,
+        root: Cid,
+        position: u64,
+        want: usize,
+    ) -> Result<Fetched, ReadFailed> {
+        use bitswap::Block;
+
+        let target_end = position + want as u64;
+        let visit = IdleFileVisit::default().with_target_range(position..target_end);
+
+        let Block { cid, data } = ipfs
+            .get_block(&root)
+            .await
+            .map_err(|e| ReadFailed::Loading(root, e))?;
+
+        let mut out = Vec::with_capacity(want);
+        let mut leaves = Vec::new();
+        let mut pos = position;
+
+        let (bytes, metadata, mut visit) =
+            visit.start(&data).map_err(|e| ReadFailed::Walking(cid, e))?;
+        let filesize = metadata.file_size();
+        let mut is_first_leaf = true;
+
+        if !bytes.is_empty() {
+            let back_truncated = pos + bytes.len() as u64 >= target_end;
+            record_leaf(&mut leaves, cid, &data, pos, bytes.len(), is_first_leaf, back_truncated);
+            is_first_leaf = false;
+            pos += bytes.len() as u64;
+            out.extend_from_slice(bytes);
+        }
+
+        let mut visit = match visit.take() {
+            Some(v) => v,
+            None => return Ok(Fetched { bytes: out, leaves, filesize }),
+        };
+
+        while out.len() < want {
+            let (next, _) = visit.pending_links();
+            let Block { cid, data } = ipfs
+                .get_block(next)
+                .await
+                .map_err(|e| ReadFailed::Loading(*next, e))?;
+
+            let (bytes, next_visit) = visit
+                .continue_walk(&data)
+                .map_err(|e| ReadFailed::Walking(cid, e))?;
+
+            if !bytes.is_empty() {
+                let back_truncated = pos + bytes.len() as u64 >= target_end;
+                record_leaf(&mut leaves, cid, &data, pos, bytes.len(), is_first_leaf, back_truncated);
+                is_first_leaf = false;
+                pos += bytes.len() as u64;
+                out.extend_from_slice(bytes);
+            }
+
+            match next_visit {
+                Some(v) => visit = v,
+                None => break,
+            }
+        }
+
+        Ok(Fetched { bytes: out, leaves, filesize })
+    }
+}
+
+/// Returns the leaf's file-content bytes: the block's bytes directly for a
+/// raw-codec leaf (the default with `--raw-leaves`), or the embedded
+/// `Data.Data` payload for a dag-pb-wrapped leaf.
+fn leaf_payload(cid: Cid, data: &[u8]) -> Result<Vec<u8>, unixfs_pb::PbDecodeError> {
+    if cid.codec() == unixfs_pb::RAW {
+        return Ok(data.to_vec());
+    }
+
+    let node = unixfs_pb::parse_pb_node(data)?;
+    Ok(unixfs_pb::parse_unixfs_data(&node.data)?.data)
+}
+
+/// Records the leaf that produced `chunk` (a slice of length `chunk_len`
+/// starting at absolute file offset `chunk_start`) in `leaves`. Handles both
+/// raw-codec and dag-pb-wrapped leaves via [`leaf_payload`].
+///
+/// `with_target_range` clips the visited bytes to the requested window, so a
+/// partial chunk can be clipped at its front (the range started partway
+/// through the leaf), its back (the range ended partway through it), or, for
+/// a small read landing entirely inside one leaf, both. Only the very first
+/// leaf of a walk (`is_first_leaf`) can ever be front-clipped — every
+/// subsequent leaf starts exactly where the previous one ended — so that's
+/// the only case needing `payload.len() - chunk_len` to recover the true
+/// leaf start; elsewhere `chunk_start` already *is* the leaf's start.
+fn record_leaf(
+    leaves: &mut Vec<(u64, CachedLeaf)>,
+    cid: Cid,
+    data: &[u8],
+    chunk_start: u64,
+    chunk_len: usize,
+    is_first_leaf: bool,
+    back_truncated: bool,
+) {
+    let payload_len = match leaf_payload(cid, data) {
+        Ok(payload) if !payload.is_empty() => payload.len(),
+        _ => return,
+    };
+
+    let (leaf_start, leaf_end) = if chunk_len == payload_len {
+        // Nothing was clipped on either side.
+        (chunk_start, chunk_start + payload_len as u64)
+    } else if is_first_leaf && !back_truncated {
+        // Front-clipped only: the visited bytes are this leaf's tail.
+        let intra_offset = (payload_len - chunk_len) as u64;
+        let leaf_start = chunk_start - intra_offset;
+        (leaf_start, leaf_start + payload_len as u64)
+    } else if !is_first_leaf {
+        // Back-clipped only (every non-first leaf starts at chunk_start):
+        // the visited bytes are this leaf's head.
+        (chunk_start, chunk_start + payload_len as u64)
+    } else {
+        // Front- and back-clipped at once: the true leaf start can't be
+        // recovered from the visited slice alone, so don't cache a guess.
+        return;
+    };
+
+    leaves.push((leaf_start, CachedLeaf { end: leaf_end, cid }));
+}
+
+fn apply_seek_offset(base: u64, delta: i64) -> io::Result<u64> {
+    let result = base as i64 + delta;
+    if result < 0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "seek to a negative position",
+        ))
+    } else {
+        Ok(result as u64)
+    }
+}
+
+impl<Types: IpfsTypes> AsyncRead for UnixFsFile<Types> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if !this.ready.is_empty() {
+                let available = this.ready.make_contiguous();
+                let n = buf.remaining().min(available.len());
+                buf.put_slice(&available[..n]);
+                this.ready.drain(..n);
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(filesize) = this.filesize {
+                if this.position >= filesize {
+                    return Poll::Ready(Ok(())); // EOF
+                }
+            }
+
+            if this.pending_read.is_none() {
+                let want = buf.remaining();
+                let position = this.position;
+                this.pending_read = Some(Box::pin(Self::fetch_at(
+                    this.ipfs.clone(),
+                    this.root,
+                    this.ranges.clone(),
+                    position,
+                    want,
+                )));
+            }
+
+            let fut = this.pending_read.as_mut().expect("just set above");
+            match fut.as_mut().poll(cx) {
+                Poll::Ready(Ok(fetched)) => {
+                    this.pending_read = None;
+                    this.merge(fetched.leaves, fetched.filesize);
+
+                    if fetched.bytes.is_empty() {
+                        return Poll::Ready(Ok(())); // EOF
+                    }
+
+                    this.position += fetched.bytes.len() as u64;
+                    this.ready.extend(fetched.bytes);
+                    // loop back around to copy the freshly read bytes into `buf`
+                }
+                Poll::Ready(Err(e)) => {
+                    this.pending_read = None;
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<Types: IpfsTypes> AsyncSeek for UnixFsFile<Types> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.ready.clear();
+        this.pending_read = None;
+        this.pending_seek = None;
+
+        match position {
+            SeekFrom::Start(n) => {
+                this.position = n;
+                Ok(())
+            }
+            SeekFrom::Current(n) => {
+                this.position = apply_seek_offset(this.position, n)?;
+                Ok(())
+            }
+            SeekFrom::End(n) => match this.filesize {
+                Some(filesize) => {
+                    this.position = apply_seek_offset(filesize, n)?;
+                    Ok(())
+                }
+                None => {
+                    // The filesize is only known by visiting the root node;
+                    // poll_complete resolves it and finishes the seek.
+                    let fut = Box::pin(Self::walk_from_root(this.ipfs.clone(), this.root, 0, 0));
+                    this.pending_seek = Some((n, fut));
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let (delta, fut) = match this.pending_seek.as_mut() {
+            Some(pending) => pending,
+            None => return Poll::Ready(Ok(this.position)),
+        };
+
+        match fut.as_mut().poll(cx) {
+            Poll::Ready(Ok(fetched)) => {
+                let delta = *delta;
+                let filesize = fetched.filesize.unwrap_or(0);
+                this.pending_seek = None;
+                this.merge(fetched.leaves, Some(filesize));
+                this.position = apply_seek_offset(filesize, delta)?;
+                Poll::Ready(Ok(this.position))
+            }
+            Poll::Ready(Err(e)) => {
+                this.pending_seek = None;
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Types of failures which can occur while reading a UnixFS file at an
+/// arbitrary offset.
+#[derive(Debug)]
+pub enum ReadFailed {
+    /// Failure to load the block.
+    Loading(Cid, Error),
+    /// Processing of the block failed.
+    Walking(Cid, FileReadFailed),
+    /// The block did not decode as a dag-pb node or UnixFS `Data` message.
+    Decoding(Cid, String),
+}
+
+impl fmt::Display for ReadFailed {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ReadFailed::*;
+        match self {
+            Loading(cid, e) => write!(fmt, "loading of {} failed: {}", cid, e),
+            Walking(cid, e) => write!(fmt, "failed to walk {}: {}", cid, e),
+            Decoding(cid, e) => write!(fmt, "failed to decode {}: {}", cid, e),
+        }
+    }
+}
+
+impl std::error::Error for ReadFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use ReadFailed::*;
+        match self {
+            Loading(_, _) => None,
+            Walking(_, e) => Some(e),
+            Decoding(_, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld::multihash::{Code, MultihashDigest};
+    use unixfs_pb::{PbNode, UnixFsData, UnixFsType};
+
+    fn raw_cid(bytes: &[u8]) -> Cid {
+        Cid::new_v1(unixfs_pb::RAW, Code::Sha2_256.digest(bytes))
+    }
+
+    fn dag_pb_cid(bytes: &[u8]) -> Cid {
+        Cid::new_v1(unixfs_pb::DAG_PB, Code::Sha2_256.digest(bytes))
+    }
+
+    #[test]
+    fn leaf_payload_reads_raw_codec_leaves_directly() {
+        let content = b"hello raw leaf".to_vec();
+        let cid = raw_cid(&content);
+
+        let payload = leaf_payload(cid, &content).expect("raw leaf reads directly");
+        assert_eq!(payload, content);
+    }
+
+    #[test]
+    fn leaf_payload_unwraps_dag_pb_leaves() {
+        let content = b"hello dag-pb leaf".to_vec();
+        let node = PbNode {
+            data: unixfs_pb::encode_unixfs_data(&UnixFsData {
+                kind: Some(UnixFsType::File),
+                data: content.clone(),
+                filesize: Some(content.len() as u64),
+                ..Default::default()
+            }),
+            links: Vec::new(),
+        };
+        let encoded = unixfs_pb::encode_pb_node(&node);
+        let cid = dag_pb_cid(&encoded);
+
+        let payload = leaf_payload(cid, &encoded).expect("dag-pb leaf decodes");
+        assert_eq!(payload, content);
+    }
+
+    #[test]
+    fn record_leaf_recovers_front_clipped_first_leaf_as_a_tail() {
+        let content = b"0123456789".to_vec();
+        let cid = raw_cid(&content);
+
+        let mut leaves = Vec::new();
+        // The first leaf of a walk starting mid-leaf: only "6789" (offset 6)
+        // was visited, and there was more file after it (not back-truncated).
+        record_leaf(&mut leaves, cid, &content, 106, 4, true, false);
+
+        assert_eq!(leaves.len(), 1);
+        let (start, leaf) = leaves[0];
+        assert_eq!(start, 100);
+        assert_eq!(leaf.end, 110);
+        assert_eq!(leaf.cid, cid);
+    }
+
+    #[test]
+    fn record_leaf_recovers_back_clipped_later_leaf_as_a_head() {
+        let content = b"0123456789".to_vec();
+        let cid = raw_cid(&content);
+
+        let mut leaves = Vec::new();
+        // A non-first leaf where the read's target range ended partway
+        // through it: only "0123" (the head) was visited.
+        record_leaf(&mut leaves, cid, &content, 200, 4, false, true);
+
+        assert_eq!(leaves.len(), 1);
+        let (start, leaf) = leaves[0];
+        assert_eq!(start, 200);
+        assert_eq!(leaf.end, 210);
+    }
+
+    #[test]
+    fn record_leaf_caches_a_fully_visited_leaf() {
+        let content = b"0123456789".to_vec();
+        let cid = raw_cid(&content);
+
+        let mut leaves = Vec::new();
+        record_leaf(&mut leaves, cid, &content, 300, content.len(), false, true);
+
+        assert_eq!(leaves.len(), 1);
+        let (start, leaf) = leaves[0];
+        assert_eq!(start, 300);
+        assert_eq!(leaf.end, 310);
+    }
+
+    #[test]
+    fn record_leaf_skips_a_leaf_clipped_on_both_ends() {
+        let content = b"0123456789".to_vec();
+        let cid = raw_cid(&content);
+
+        let mut leaves = Vec::new();
+        // A small read landing entirely inside the first (and only) leaf:
+        // clipped at both the front and the back. The true leaf start can't
+        // be recovered from this alone, so it must not be cached — caching
+        // a wrong guess here would previously have underflowed the u64
+        // subtraction or poisoned the range with the wrong bounds.
+        record_leaf(&mut leaves, cid, &content, 2, 3, true, true);
+
+        assert!(leaves.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Bucket lookup helpers for UnixFS HAMT-sharded directories.
+//!
+//! A shard hashes each entry name with murmur3-x64-64 and consumes
+//! `log2(fanout)` bits per level, most significant bits first (go-ipfs reads
+//! the digest as a big-endian byte stream, so level 0 is the top `bits` bits
+//! of the hash), to pick which of the `fanout` buckets the entry lives in at
+//! that level.
+
+/// Hashes `name` the way a HAMT shard does: murmur3-x64-128 with seed 0,
+/// keeping the low 64 bits of the digest.
+pub(crate) fn name_hash(name: &str) -> u64 {
+    murmur3_x64_128(name.as_bytes(), 0).0
+}
+
+/// Extracts the bucket index for `level` (0-based) out of a name's hash,
+/// consuming `bits` bits per level starting from the most significant end.
+pub(crate) fn bucket_index(hash: u64, bits: u32, level: u32) -> u64 {
+    let consumed = bits * level;
+    if consumed >= 64 {
+        return 0;
+    }
+
+    if consumed + bits <= 64 {
+        // Full `bits`-wide window still fits within the 64-bit hash.
+        let shift = 64 - bits - consumed;
+        (hash >> shift) & ((1u64 << bits) - 1)
+    } else {
+        // Fewer than `bits` bits remain; take whatever is left at the bottom.
+        hash & ((1u64 << (64 - consumed)) - 1)
+    }
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^= k >> 33;
+    k
+}
+
+/// Standard murmur3-x64-128 (seed variant), returning the `(h1, h2)` pair of
+/// 64-bit halves of the digest.
+fn murmur3_x64_128(data: &[u8], seed: u64) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+    let len = data.len() as u64;
+    let nblocks = data.len() / 16;
+
+    let mut h1 = seed;
+    let mut h2 = seed;
+
+    for i in 0..nblocks {
+        let block = &data[i * 16..i * 16 + 16];
+        let mut k1 = u64::from_le_bytes(block[0..8].try_into().unwrap());
+        let mut k2 = u64::from_le_bytes(block[8..16].try_into().unwrap());
+
+        k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(27).wrapping_add(h2);
+        h1 = h1.wrapping_mul(5).wrapping_add(0x52dc_e729);
+
+        k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 ^= k2;
+        h2 = h2.rotate_left(31).wrapping_add(h1);
+        h2 = h2.wrapping_mul(5).wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = &data[nblocks * 16..];
+    let mut k1 = 0u64;
+    let mut k2 = 0u64;
+
+    if tail.len() > 8 {
+        for i in (8..tail.len()).rev() {
+            k2 ^= (tail[i] as u64) << ((i - 8) * 8);
+        }
+        k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 ^= k2;
+    }
+
+    if !tail.is_empty() {
+        for i in (0..tail.len().min(8)).rev() {
+            k1 ^= (tail[i] as u64) << (i * 8);
+        }
+        k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 ^= k1;
+    }
+
+    h1 ^= len;
+    h2 ^= len;
+
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+
+    (h1, h2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn murmur3_of_empty_input_is_zero() {
+        // With seed 0 and no blocks or tail to mix in, MurmurHash3_x64_128("")
+        // is the well known all-zero vector.
+        assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+    }
+
+    #[test]
+    fn bucket_index_consumes_most_significant_bits_first() {
+        let hash = 0xF0F1_F2F3_F4F5_F6F7u64;
+        // 8 bits per level (fanout 256): level 0 is the hash's top byte.
+        assert_eq!(bucket_index(hash, 8, 0), 0xF0);
+        assert_eq!(bucket_index(hash, 8, 1), 0xF1);
+        assert_eq!(bucket_index(hash, 8, 7), 0xF7);
+    }
+
+    #[test]
+    fn bucket_index_is_zero_once_the_hash_is_exhausted() {
+        assert_eq!(bucket_index(0xFFFF_FFFF_FFFF_FFFF, 8, 8), 0);
+    }
+
+    #[test]
+    fn name_hash_is_deterministic_and_distinguishes_names() {
+        assert_eq!(name_hash("same"), name_hash("same"));
+        assert_ne!(name_hash("a"), name_hash("b"));
+    }
+}
@@ -0,0 +1,224 @@
+use super::unixfs_pb::{self, PbDecodeError, UnixFsType};
+use crate::{Error, Ipfs, IpfsTypes};
+use async_stream::stream;
+use futures::stream::Stream;
+use libipld::cid::Cid;
+use std::borrow::Borrow;
+use std::fmt;
+
+/// A single entry in a UnixFS directory listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The entry's name within its directory.
+    pub name: String,
+    /// The Cid of the entry, which may itself be a file or a directory.
+    pub cid: Cid,
+    /// Cumulative size of the entry's subgraph, as recorded in the parent
+    /// link's `Tsize`.
+    pub size: u64,
+}
+
+/// Lists the immediate contents of the UnixFS directory pointed at by `cid`.
+///
+/// HAMT-sharded directories are flattened transparently: callers see one
+/// [`DirEntry`] per named child, never the internal shard nodes that make up
+/// the shard's tree.
+pub fn ls<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    cid: Cid,
+) -> impl Stream<Item = Result<DirEntry, ListingFailed>> + Send + 'a
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
+    use bitswap::Block;
+
+    stream! {
+        // Depth-first queue of block Cids still to be visited: the root, plus
+        // any HAMT shard children discovered along the way.
+        let mut queue = vec![cid];
+
+        while let Some(cid) = queue.pop() {
+            let borrow = ipfs.borrow();
+            let Block { cid, data } = match borrow.get_block(&cid).await {
+                Ok(block) => block,
+                Err(e) => {
+                    yield Err(ListingFailed::Loading(cid, e));
+                    return;
+                }
+            };
+
+            let node = match unixfs_pb::parse_pb_node(&data) {
+                Ok(node) => node,
+                Err(e) => {
+                    yield Err(ListingFailed::Decoding(cid, e));
+                    return;
+                }
+            };
+
+            let fsdata = match unixfs_pb::parse_unixfs_data(&node.data) {
+                Ok(fsdata) => fsdata,
+                Err(e) => {
+                    yield Err(ListingFailed::Decoding(cid, e));
+                    return;
+                }
+            };
+
+            match fsdata.kind {
+                Some(UnixFsType::Directory) => {
+                    for link in node.links {
+                        yield Ok(DirEntry { name: link.name, cid: link.cid, size: link.tsize });
+                    }
+                }
+                Some(UnixFsType::HamtShard) => {
+                    let fanout = fsdata.fanout.unwrap_or(256);
+                    let prefix_len = shard_prefix_len(fanout);
+
+                    for link in node.links {
+                        match split_shard_link(&link.name, prefix_len) {
+                            Some(ShardLink::Child) => queue.push(link.cid),
+                            Some(ShardLink::Entry(name)) => {
+                                yield Ok(DirEntry { name: name.to_string(), cid: link.cid, size: link.tsize });
+                            }
+                            None => {
+                                yield Err(ListingFailed::MalformedShardLink(cid, link.name));
+                                return;
+                            }
+                        }
+                    }
+                }
+                Some(other) => {
+                    yield Err(ListingFailed::NotADirectory(cid, other));
+                    return;
+                }
+                None => {
+                    yield Err(ListingFailed::MissingUnixFsType(cid));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Number of hex digits a HAMT shard prefixes its link names with, given the
+/// shard's fanout (always a power of two).
+pub(crate) fn shard_prefix_len(fanout: u64) -> usize {
+    let bits = fanout.trailing_zeros();
+    ((bits + 3) / 4) as usize
+}
+
+enum ShardLink<'a> {
+    /// The link name is exactly the bucket prefix: a pointer to a child shard.
+    Child,
+    /// The link name is `prefix + name`: a terminal directory entry.
+    Entry(&'a str),
+}
+
+fn split_shard_link(name: &str, prefix_len: usize) -> Option<ShardLink<'_>> {
+    if name.len() < prefix_len || !name.is_char_boundary(prefix_len) {
+        return None;
+    }
+
+    let (prefix, rest) = name.split_at(prefix_len);
+    if !prefix.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_lowercase()) {
+        return None;
+    }
+
+    if rest.is_empty() {
+        Some(ShardLink::Child)
+    } else {
+        Some(ShardLink::Entry(rest))
+    }
+}
+
+/// Types of failures which can occur while listing a UnixFS directory.
+#[derive(Debug)]
+pub enum ListingFailed {
+    /// Failure to load the block.
+    Loading(Cid, Error),
+    /// The block did not decode as a dag-pb node or UnixFS `Data` message.
+    Decoding(Cid, PbDecodeError),
+    /// The node is a UnixFS node, but not a directory or HAMT shard.
+    NotADirectory(Cid, UnixFsType),
+    /// The node had no UnixFS `Data` message at all.
+    MissingUnixFsType(Cid),
+    /// A link under a HAMT shard node did not follow the `<prefix>` or
+    /// `<prefix><name>` naming convention.
+    MalformedShardLink(Cid, String),
+}
+
+impl fmt::Display for ListingFailed {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ListingFailed::*;
+        match self {
+            Loading(cid, e) => write!(fmt, "loading of {} failed: {}", cid, e),
+            Decoding(cid, e) => write!(fmt, "failed to decode {}: {}", cid, e),
+            NotADirectory(cid, kind) => write!(fmt, "{} is a {}, not a directory", cid, kind),
+            MissingUnixFsType(cid) => write!(fmt, "{} has no UnixFS Data message", cid),
+            MalformedShardLink(cid, name) => {
+                write!(fmt, "{} has a malformed HAMT shard link named {:?}", cid, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ListingFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use ListingFailed::*;
+
+        match self {
+            Loading(_, _) => {
+                // FIXME: anyhow::Error cannot be given out as source.
+                None
+            }
+            Decoding(_, e) => Some(e),
+            NotADirectory(_, _) | MissingUnixFsType(_) | MalformedShardLink(_, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::hamt;
+
+    #[test]
+    fn shard_prefix_len_matches_fanout() {
+        assert_eq!(shard_prefix_len(256), 2);
+        assert_eq!(shard_prefix_len(16), 1);
+    }
+
+    #[test]
+    fn split_shard_link_round_trips_bucket_index_prefixes() {
+        // Reproduces a minimal two-entry HAMT shard fixture: a child-shard
+        // pointer link (bare prefix) and a terminal entry link
+        // (prefix + name), both keyed off the same bucket_index the resolver
+        // uses, so the link names `resolve`'s descent looks for are exactly
+        // what this module's parser accepts.
+        let fanout = 256u64;
+        let bits = fanout.trailing_zeros();
+        let prefix_len = shard_prefix_len(fanout);
+
+        let hash = hamt::name_hash("entry.txt");
+        let bucket = hamt::bucket_index(hash, bits, 0);
+        let prefix = format!("{:0width$X}", bucket, width = prefix_len);
+
+        let child_link_name = prefix.clone();
+        let entry_link_name = format!("{}entry.txt", prefix);
+
+        assert!(matches!(
+            split_shard_link(&child_link_name, prefix_len),
+            Some(ShardLink::Child)
+        ));
+        match split_shard_link(&entry_link_name, prefix_len) {
+            Some(ShardLink::Entry(name)) => assert_eq!(name, "entry.txt"),
+            other => panic!("expected a terminal entry, got {:?}", other.is_some()),
+        }
+    }
+
+    #[test]
+    fn split_shard_link_rejects_lowercase_hex_and_short_names() {
+        assert!(split_shard_link("af", 2).is_none());
+        assert!(split_shard_link("A", 2).is_none());
+    }
+}
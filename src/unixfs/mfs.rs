@@ -0,0 +1,435 @@
+//! Mutable File System (MFS): a mutable, path-addressed view over UnixFS,
+//! backed by a single persisted root [`Cid`] that every write re-derives.
+//!
+//! Each mutating call resolves its path through the current root's UnixFS
+//! directories, produces a new leaf node, and re-links parents bottom-up to
+//! compute a new root, swapping the stored root atomically. Reads delegate
+//! to the directory-resolution logic `ls`/`resolve` already use, and to
+//! [`cat`](super::cat::cat) once a path resolves to a file.
+//!
+//! Files are currently stored as a single UnixFS leaf node with the content
+//! embedded directly in `Data.Data` (the small-file representation); there
+//! is no chunking of large writes into a multi-block DAG yet.
+
+use super::cat::{cat, TraversalFailed};
+use super::unixfs_pb::{self, cid_for_dag_pb, PbLink, PbNode, UnixFsData, UnixFsType};
+use crate::{Error, Ipfs, IpfsTypes};
+use futures::stream::{Stream, StreamExt};
+use libipld::cid::Cid;
+use std::fmt;
+use std::ops::Range;
+use tokio::sync::Mutex;
+
+/// A mutable, path-addressed filesystem view rooted at a single, persisted
+/// [`Cid`] that every write atomically replaces.
+pub struct Mfs<Types: IpfsTypes> {
+    ipfs: Ipfs<Types>,
+    root: Mutex<Cid>,
+}
+
+/// The result of [`Mfs::mfs_stat`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MfsStat {
+    pub cid: Cid,
+    /// Cumulative size of the entry's subgraph (a directory's `Tsize`, or a
+    /// file's byte length).
+    pub size: u64,
+    pub is_directory: bool,
+}
+
+impl<Types: IpfsTypes> Mfs<Types> {
+    /// Opens an MFS view with `root` as the starting root. Passing the Cid
+    /// of an empty UnixFS directory gives an empty MFS.
+    pub fn new(ipfs: Ipfs<Types>, root: Cid) -> Self {
+        Mfs {
+            ipfs,
+            root: Mutex::new(root),
+        }
+    }
+
+    /// The current root Cid.
+    pub async fn root(&self) -> Cid {
+        *self.root.lock().await
+    }
+
+    /// Creates a directory at `path`. If `parents` is set, missing
+    /// intermediate directories are created as needed, like `mkdir -p`;
+    /// otherwise a missing intermediate directory is an error.
+    pub async fn mfs_mkdir(&self, path: &str, parents: bool) -> Result<(), MfsError> {
+        let empty = self.write_directory(&PbNode::default(), UnixFsType::Directory).await?;
+
+        self.update_leaf(path, parents, |existing| match existing {
+            Some(link) => Err(MfsError::AlreadyExists(link.name)),
+            None => Ok(Some(empty)),
+        })
+        .await
+    }
+
+    /// Writes `bytes` at `offset` into the file at `path`, creating it (and,
+    /// if missing, its parent directories) if necessary.
+    ///
+    /// The existing content is read and the new root computed while holding
+    /// `self.root`'s lock for the whole operation, not just parts of it: a
+    /// version that released the lock between reading the old content and
+    /// relinking the new root would let two concurrent `mfs_write`s read the
+    /// same starting point and have one silently clobber the other's update.
+    pub async fn mfs_write(&self, path: &str, bytes: &[u8], offset: u64) -> Result<(), MfsError> {
+        let segments = split_path(path)?;
+        let (parents, name) = segments.split_last().ok_or(MfsError::IsRoot)?;
+
+        let mut root = self.root.lock().await;
+
+        let existing_cid = self.lookup(*root, parents, name).await.ok();
+        let mut content = match existing_cid {
+            Some(cid) => self.read_whole_file(cid).await?,
+            None => Vec::new(),
+        };
+
+        let end = offset as usize + bytes.len();
+        if content.len() < end {
+            content.resize(end, 0);
+        }
+        content[offset as usize..end].copy_from_slice(bytes);
+
+        let link = self.write_file(&content).await?;
+
+        self.update_leaf_locked(&mut root, path, true, |_existing| Ok(Some(link)))
+            .await
+    }
+
+    /// Streams the bytes of the file at `path`. Delegates to
+    /// [`cat`](super::cat::cat) once the path has been resolved to a Cid.
+    pub async fn mfs_read(
+        &self,
+        path: &str,
+        range: Option<Range<u64>>,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, TraversalFailed>> + Send + 'static, MfsError>
+    {
+        let root = *self.root.lock().await;
+        let cid = self.resolve(root, path).await?;
+        Ok(cat(self.ipfs.clone(), cid, range))
+    }
+
+    /// Copies the entry at `src` to `dst`, without removing `src`.
+    pub async fn mfs_cp(&self, src: &str, dst: &str) -> Result<(), MfsError> {
+        let root = *self.root.lock().await;
+        let stat = self.stat_at(root, src).await?;
+        let link = PbLink {
+            cid: stat.cid,
+            name: String::new(),
+            tsize: self.dag_size(stat.cid).await?,
+        };
+
+        self.update_leaf(dst, true, |existing| match existing {
+            Some(link) => Err(MfsError::AlreadyExists(link.name)),
+            None => Ok(Some(link)),
+        })
+        .await
+    }
+
+    /// Moves the entry at `src` to `dst`.
+    pub async fn mfs_mv(&self, src: &str, dst: &str) -> Result<(), MfsError> {
+        self.mfs_cp(src, dst).await?;
+        self.mfs_rm(src, true).await
+    }
+
+    /// Removes the entry at `path`. A non-empty directory requires
+    /// `recursive`.
+    pub async fn mfs_rm(&self, path: &str, recursive: bool) -> Result<(), MfsError> {
+        if !recursive {
+            let root = *self.root.lock().await;
+            let stat = self.stat_at(root, path).await?;
+            if stat.is_directory {
+                let (node, _) = self.load(stat.cid).await?;
+                if !node.links.is_empty() {
+                    return Err(MfsError::DirectoryNotEmpty(path.to_string()));
+                }
+            }
+        }
+
+        self.update_leaf(path, false, |existing| match existing {
+            Some(_) => Ok(None),
+            None => Err(MfsError::NotFound(path.to_string())),
+        })
+        .await
+    }
+
+    /// Returns metadata about the entry at `path`.
+    pub async fn mfs_stat(&self, path: &str) -> Result<MfsStat, MfsError> {
+        let root = *self.root.lock().await;
+        self.stat_at(root, path).await
+    }
+
+    /// Returns the Cid the entry at `path` currently resolves to. Since
+    /// every mutation already updates the persisted root before returning,
+    /// this is equivalent to `mfs_stat(path).cid`; it exists to mirror the
+    /// "flush" operation of other MFS implementations.
+    pub async fn mfs_flush(&self, path: &str) -> Result<Cid, MfsError> {
+        Ok(self.mfs_stat(path).await?.cid)
+    }
+
+    async fn stat_at(&self, root: Cid, path: &str) -> Result<MfsStat, MfsError> {
+        let cid = self.resolve(root, path).await?;
+        let (node, fsdata) = self.load(cid).await?;
+        let is_directory = matches!(fsdata.kind, Some(UnixFsType::Directory) | Some(UnixFsType::HamtShard));
+        let size = if is_directory {
+            let encoded = unixfs_pb::encode_pb_node(&node);
+            encoded.len() as u64 + node.links.iter().map(|l| l.tsize).sum::<u64>()
+        } else {
+            fsdata.filesize.unwrap_or_else(|| fsdata.data.len() as u64)
+        };
+
+        Ok(MfsStat { cid, size, is_directory })
+    }
+
+    /// Computes the on-wire `Tsize` for `cid`'s node: its own encoded byte
+    /// length, plus (for a directory) the cumulative `Tsize` of every child
+    /// link. This is the cumulative DAG size a freshly-created [`PbLink`]
+    /// should carry -- matching what [`Mfs::write_file`] and
+    /// [`Mfs::write_directory`] record for nodes they create -- and is
+    /// distinct from [`MfsStat::size`], which reports a file's content byte
+    /// length rather than its encoded block size.
+    async fn dag_size(&self, cid: Cid) -> Result<u64, MfsError> {
+        let (node, _) = self.load(cid).await?;
+        let encoded = unixfs_pb::encode_pb_node(&node);
+        Ok(encoded.len() as u64 + node.links.iter().map(|l| l.tsize).sum::<u64>())
+    }
+
+    async fn resolve(&self, root: Cid, path: &str) -> Result<Cid, MfsError> {
+        let segments = split_path(path)?;
+        if segments.is_empty() {
+            return Ok(root);
+        }
+        super::resolve::resolve(&self.ipfs, root, &segments)
+            .await
+            .map_err(|e| MfsError::Resolving(path.to_string(), e))
+    }
+
+    async fn lookup(&self, root: Cid, parents: &[&str], name: &str) -> Result<Cid, MfsError> {
+        let dir = self.resolve(root, &parents.join("/")).await?;
+        let (node, _) = self.load(dir).await?;
+        node.links
+            .into_iter()
+            .find(|link| link.name == name)
+            .map(|link| link.cid)
+            .ok_or_else(|| MfsError::NotFound(name.to_string()))
+    }
+
+    async fn read_whole_file(&self, cid: Cid) -> Result<Vec<u8>, MfsError> {
+        let mut out = Vec::new();
+        let mut stream = Box::pin(cat(&self.ipfs, cid, None));
+        while let Some(chunk) = stream.next().await {
+            out.extend_from_slice(&chunk.map_err(MfsError::Reading)?);
+        }
+        Ok(out)
+    }
+
+    async fn load(&self, cid: Cid) -> Result<(PbNode, UnixFsData), MfsError> {
+        use bitswap::Block;
+
+        let Block { data, .. } = self
+            .ipfs
+            .get_block(&cid)
+            .await
+            .map_err(|e| MfsError::Loading(cid, e))?;
+        let node = unixfs_pb::parse_pb_node(&data).map_err(|e| MfsError::Decoding(cid, e.to_string()))?;
+        let fsdata =
+            unixfs_pb::parse_unixfs_data(&node.data).map_err(|e| MfsError::Decoding(cid, e.to_string()))?;
+        Ok((node, fsdata))
+    }
+
+    async fn write_directory(&self, node: &PbNode, kind: UnixFsType) -> Result<PbLink, MfsError> {
+        let mut node = node.clone();
+        node.links.sort_by(|a, b| a.name.cmp(&b.name));
+        node.data = unixfs_pb::encode_unixfs_data(&UnixFsData {
+            kind: Some(kind),
+            ..Default::default()
+        });
+
+        let encoded = unixfs_pb::encode_pb_node(&node);
+        let tsize = encoded.len() as u64 + node.links.iter().map(|l| l.tsize).sum::<u64>();
+        let cid = self.put(encoded).await?;
+
+        Ok(PbLink { cid, name: String::new(), tsize })
+    }
+
+    async fn write_file(&self, content: &[u8]) -> Result<PbLink, MfsError> {
+        let node = PbNode {
+            data: unixfs_pb::encode_unixfs_data(&UnixFsData {
+                kind: Some(UnixFsType::File),
+                data: content.to_vec(),
+                filesize: Some(content.len() as u64),
+                ..Default::default()
+            }),
+            links: Vec::new(),
+        };
+
+        let encoded = unixfs_pb::encode_pb_node(&node);
+        let tsize = encoded.len() as u64;
+        let cid = self.put(encoded).await?;
+
+        Ok(PbLink { cid, name: String::new(), tsize })
+    }
+
+    async fn put(&self, data: Vec<u8>) -> Result<Cid, MfsError> {
+        use bitswap::Block;
+
+        let cid = cid_for_dag_pb(&data);
+        let data = data.into_boxed_slice();
+        self.ipfs
+            .put_block(Block { cid, data })
+            .await
+            .map_err(|e| MfsError::Writing(e))?;
+        Ok(cid)
+    }
+
+    /// Walks `path` from the current root, creating missing intermediate
+    /// directories when `create_dirs` is set, calls `update_leaf` with the
+    /// existing link at the final segment (if any), and relinks every
+    /// ancestor bottom-up to reflect the leaf's new value before atomically
+    /// replacing the stored root.
+    async fn update_leaf<F>(&self, path: &str, create_dirs: bool, update_leaf: F) -> Result<(), MfsError>
+    where
+        F: FnOnce(Option<PbLink>) -> Result<Option<PbLink>, MfsError>,
+    {
+        let mut root = self.root.lock().await;
+        self.update_leaf_locked(&mut root, path, create_dirs, update_leaf).await
+    }
+
+    /// The body of [`Mfs::update_leaf`], taking the root as an already-locked
+    /// `&mut Cid` so that callers which need to do their own reads under the
+    /// same critical section (e.g. [`Mfs::mfs_write`], which must read the
+    /// existing file content and compute the new block without releasing the
+    /// lock in between) can fold that work and this one into a single
+    /// lock-and-swap instead of two, which would otherwise open a window for
+    /// a concurrent writer to update the root in between.
+    async fn update_leaf_locked<F>(
+        &self,
+        root: &mut Cid,
+        path: &str,
+        create_dirs: bool,
+        update_leaf: F,
+    ) -> Result<(), MfsError>
+    where
+        F: FnOnce(Option<PbLink>) -> Result<Option<PbLink>, MfsError>,
+    {
+        let segments = split_path(path)?;
+        let (parents, name) = segments.split_last().ok_or(MfsError::IsRoot)?;
+
+        let mut frames = Vec::with_capacity(parents.len());
+        let mut current = *root;
+        for segment in parents {
+            let (node, _) = self.load(current).await?;
+            let next = node.links.iter().find(|l| l.name == *segment).map(|l| l.cid);
+            frames.push((segment.to_string(), node));
+            current = match next {
+                Some(cid) => cid,
+                None if create_dirs => {
+                    let empty = self.write_directory(&PbNode::default(), UnixFsType::Directory).await?;
+                    empty.cid
+                }
+                None => return Err(MfsError::NotFound(segment.to_string())),
+            };
+        }
+
+        let (mut leaf_dir, _) = self.load(current).await?;
+        let existing = leaf_dir.links.iter().find(|l| l.name == *name).cloned();
+        let new_link = update_leaf(existing)?;
+
+        leaf_dir.links.retain(|l| l.name != *name);
+        if let Some(mut link) = new_link {
+            link.name = name.to_string();
+            leaf_dir.links.push(link);
+        }
+
+        let mut child_link = self.write_directory(&leaf_dir, UnixFsType::Directory).await?;
+
+        for (segment, mut dir) in frames.into_iter().rev() {
+            dir.links.retain(|l| l.name != segment);
+            child_link.name = segment;
+            dir.links.push(child_link);
+            child_link = self.write_directory(&dir, UnixFsType::Directory).await?;
+        }
+
+        *root = child_link.cid;
+        Ok(())
+    }
+}
+
+fn split_path(path: &str) -> Result<Vec<&str>, MfsError> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    Ok(segments)
+}
+
+/// Types of failures which can occur while operating on the MFS.
+#[derive(Debug)]
+pub enum MfsError {
+    /// The path named the MFS root itself, which cannot be written to or
+    /// removed directly.
+    IsRoot,
+    /// Failed to resolve a path to a Cid.
+    Resolving(String, super::resolve::ResolveError),
+    /// Failure to load the block.
+    Loading(Cid, Error),
+    /// Failure to read a file's bytes.
+    Reading(TraversalFailed),
+    /// Failure to write a new block.
+    Writing(Error),
+    /// The block did not decode as a dag-pb node or UnixFS `Data` message.
+    Decoding(Cid, String),
+    /// No entry exists at the given path.
+    NotFound(String),
+    /// An entry already exists at the given path.
+    AlreadyExists(String),
+    /// Removing a non-empty directory was attempted without `recursive`.
+    DirectoryNotEmpty(String),
+}
+
+impl fmt::Display for MfsError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use MfsError::*;
+        match self {
+            IsRoot => write!(fmt, "the MFS root cannot be targeted directly"),
+            Resolving(path, e) => write!(fmt, "failed to resolve {:?}: {}", path, e),
+            Loading(cid, e) => write!(fmt, "loading of {} failed: {}", cid, e),
+            Reading(e) => write!(fmt, "failed to read file contents: {}", e),
+            Writing(e) => write!(fmt, "failed to write a new block: {}", e),
+            Decoding(cid, e) => write!(fmt, "failed to decode {}: {}", cid, e),
+            NotFound(path) => write!(fmt, "no entry at {:?}", path),
+            AlreadyExists(path) => write!(fmt, "an entry already exists at {:?}", path),
+            DirectoryNotEmpty(path) => write!(fmt, "{:?} is a non-empty directory", path),
+        }
+    }
+}
+
+impl std::error::Error for MfsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use MfsError::*;
+        match self {
+            Resolving(_, e) => Some(e),
+            Reading(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// `update_leaf`, `write_directory`, `write_file` and friends all round-trip
+// through `self.ipfs.{get,put}_block`, so a real write -> flush -> read
+// round trip needs something implementing `Ipfs<Types>`/`IpfsTypes` -- and
+// those are defined at the crate root, which isn't part of this source
+// snapshot (only `src/unixfs/` is present here, with no `Cargo.toml` or
+// `lib.rs`), so there's nothing to build a block-store double against or
+// compile it with. Still no test double is possible for that reason, not
+// for lack of trying. The path-splitting logic everything above sits on
+// top of has no such dependency, so it's covered directly instead.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_path_drops_empty_segments() {
+        assert_eq!(split_path("a/b/c").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(split_path("/a//b/").unwrap(), vec!["a", "b"]);
+        assert!(split_path("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+//! UnixFS operations built on top of the bitswap block store: reading file
+//! contents (`cat`), listing directories and resolving paths (`ls`,
+//! `resolve`), walking the underlying IPLD link graph (`refs`), a mutable,
+//! path-addressed view over it all (`mfs`), a seekable file handle
+//! (`file_handle`), and content-type sniffing (`content_type`).
+
+pub mod cat;
+pub mod content_type;
+pub mod file_handle;
+mod hamt;
+pub mod ls;
+pub mod mfs;
+pub mod refs;
+pub mod resolve;
+mod unixfs_pb;
+
+pub use cat::{cat, cat_with, cat_with_metadata, CatOptions, Metadata};
+pub use content_type::ContentType;
+pub use file_handle::UnixFsFile;
+pub use ls::DirEntry;
+pub use mfs::{Mfs, MfsStat};
+pub use refs::{Edge, IpldRefsBuilder};
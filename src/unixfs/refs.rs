@@ -0,0 +1,227 @@
+use super::unixfs_pb::{self, DAG_PB};
+use crate::{Error, Ipfs, IpfsTypes};
+use async_stream::stream;
+use futures::stream::Stream;
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use std::borrow::Borrow;
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// One link traversed while walking the IPLD graph rooted at some Cid:
+/// `source` linked to `destination`, optionally under `name` (present for
+/// dag-pb links with a non-empty name, absent for unnamed dag-pb links and
+/// for codecs without named links such as dag-cbor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub source: Cid,
+    pub destination: Cid,
+    pub name: Option<String>,
+}
+
+/// Builds an [`Edge`] stream over the IPLD link graph reachable from a root
+/// Cid, walking the graph breadth-first one block at a time via
+/// [`Ipfs::get_block`](crate::Ipfs::get_block).
+#[derive(Debug, Clone)]
+pub struct IpldRefsBuilder {
+    max_depth: Option<u64>,
+    unique: bool,
+    download_blocks: bool,
+}
+
+impl Default for IpldRefsBuilder {
+    fn default() -> Self {
+        IpldRefsBuilder {
+            max_depth: None,
+            unique: false,
+            download_blocks: true,
+        }
+    }
+}
+
+impl IpldRefsBuilder {
+    /// Bounds the depth of emitted edges; `None` (the default) is unbounded.
+    /// The root is depth 0, so edges out of the root are emitted at depth 1.
+    pub fn max_depth(mut self, max_depth: Option<u64>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// When set, already-visited destinations are tracked and further edges
+    /// into them are suppressed.
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// When `false`, a block missing from the local store produces
+    /// [`IpldRefsError::BlockNotFound`] instead of being fetched from the
+    /// network.
+    pub fn download_blocks(mut self, download_blocks: bool) -> Self {
+        self.download_blocks = download_blocks;
+        self
+    }
+
+    /// Runs the walk, starting at `root`.
+    pub fn refs<'a, Types, MaybeOwned>(
+        self,
+        ipfs: MaybeOwned,
+        root: Cid,
+    ) -> impl Stream<Item = Result<Edge, IpldRefsError>> + Send + 'a
+    where
+        Types: IpfsTypes,
+        MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+    {
+        use bitswap::Block;
+
+        stream! {
+            let mut queue = VecDeque::new();
+            queue.push_back((root, 0u64));
+
+            let mut visited: HashSet<Cid> = HashSet::new();
+
+            while let Some((source, depth)) = queue.pop_front() {
+                if let Some(max_depth) = self.max_depth {
+                    if depth >= max_depth {
+                        continue;
+                    }
+                }
+
+                let borrow = ipfs.borrow();
+                let data = if self.download_blocks {
+                    match borrow.get_block(&source).await {
+                        Ok(Block { data, .. }) => data,
+                        Err(e) => {
+                            yield Err(IpldRefsError::Loading(source, e));
+                            return;
+                        }
+                    }
+                } else {
+                    match borrow.repo().get_block_now(&source).await {
+                        Ok(Some(Block { data, .. })) => data,
+                        Ok(None) => {
+                            yield Err(IpldRefsError::BlockNotFound(source));
+                            return;
+                        }
+                        Err(e) => {
+                            yield Err(IpldRefsError::Loading(source, e));
+                            return;
+                        }
+                    }
+                };
+
+                let links = match links_of(&source, &data) {
+                    Ok(links) => links,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                for (name, destination) in links {
+                    if self.unique {
+                        if !visited.insert(destination) {
+                            continue;
+                        }
+                    }
+
+                    yield Ok(Edge { source, destination, name });
+                    queue.push_back((destination, depth + 1));
+                }
+            }
+        }
+    }
+}
+
+/// Runs an unbounded, non-unique, network-fetching refs walk starting at
+/// `root`. Equivalent to `IpldRefsBuilder::default().refs(ipfs, root)`.
+pub fn refs<'a, Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    root: Cid,
+) -> impl Stream<Item = Result<Edge, IpldRefsError>> + Send + 'a
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>> + Send + 'a,
+{
+    IpldRefsBuilder::default().refs(ipfs, root)
+}
+
+/// Enumerates the outgoing links of a block, keeping link names where the
+/// codec has them (dag-pb); other codecs (dag-cbor and friends) have no
+/// concept of a link name, so `None` is used instead.
+fn links_of(cid: &Cid, data: &[u8]) -> Result<Vec<(Option<String>, Cid)>, IpldRefsError> {
+    if cid.codec() == DAG_PB {
+        let node = unixfs_pb::parse_pb_node(data).map_err(|e| IpldRefsError::Decoding(*cid, e.to_string()))?;
+        return Ok(node
+            .links
+            .into_iter()
+            .map(|link| {
+                let name = if link.name.is_empty() { None } else { Some(link.name) };
+                (name, link.cid)
+            })
+            .collect());
+    }
+
+    let codec = libipld::IpldCodec::try_from(cid.codec())
+        .map_err(|_| IpldRefsError::UnsupportedCodec(*cid, cid.codec()))?;
+    let ipld: Ipld = codec
+        .decode(data)
+        .map_err(|e| IpldRefsError::Decoding(*cid, e.to_string()))?;
+
+    let mut out = Vec::new();
+    collect_ipld_links(&ipld, &mut out);
+    Ok(out.into_iter().map(|cid| (None, cid)).collect())
+}
+
+fn collect_ipld_links(ipld: &Ipld, out: &mut Vec<Cid>) {
+    match ipld {
+        Ipld::Link(cid) => out.push(*cid),
+        Ipld::List(items) => {
+            for item in items {
+                collect_ipld_links(item, out);
+            }
+        }
+        Ipld::Map(map) => {
+            for value in map.values() {
+                collect_ipld_links(value, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Types of failures which can occur while walking the IPLD link graph.
+#[derive(Debug)]
+pub enum IpldRefsError {
+    /// Failure to load the block.
+    Loading(Cid, Error),
+    /// `download_blocks` was false and the block was not in the local store.
+    BlockNotFound(Cid),
+    /// The block's codec is not one this crate knows how to decode for refs.
+    UnsupportedCodec(Cid, u64),
+    /// Failure to decode the block's bytes as its codec's data model.
+    Decoding(Cid, String),
+}
+
+impl fmt::Display for IpldRefsError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IpldRefsError::*;
+        match self {
+            Loading(cid, e) => write!(fmt, "loading of {} failed: {}", cid, e),
+            BlockNotFound(cid) => write!(fmt, "{} is not present in the local block store", cid),
+            UnsupportedCodec(cid, codec) => {
+                write!(fmt, "{} uses unsupported codec 0x{:x}", cid, codec)
+            }
+            Decoding(cid, e) => write!(fmt, "failed to decode {}: {}", cid, e),
+        }
+    }
+}
+
+impl std::error::Error for IpldRefsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // FIXME: anyhow::Error and the stringified decode error cannot be
+        // given out as a source.
+        None
+    }
+}
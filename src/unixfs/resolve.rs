@@ -0,0 +1,163 @@
+use super::hamt;
+use super::ls::shard_prefix_len;
+use super::unixfs_pb::{self, PbDecodeError, PbNode, UnixFsData, UnixFsType};
+use crate::{Error, Ipfs, IpfsTypes};
+use libipld::cid::Cid;
+use std::borrow::Borrow;
+use std::fmt;
+
+/// Resolves a multi-segment IPFS path rooted at `root_cid` to the `Cid` of
+/// its final segment, walking UnixFS directories (including HAMT-sharded
+/// ones) one segment at a time.
+pub async fn resolve<Types, MaybeOwned>(
+    ipfs: MaybeOwned,
+    root_cid: Cid,
+    path: &[&str],
+) -> Result<Cid, ResolveError>
+where
+    Types: IpfsTypes,
+    MaybeOwned: Borrow<Ipfs<Types>>,
+{
+    use bitswap::Block;
+
+    let ipfs = ipfs.borrow();
+    let mut current = root_cid;
+
+    for segment in path {
+        let Block { cid, data } = ipfs
+            .get_block(&current)
+            .await
+            .map_err(|e| ResolveError::Loading(current, e))?;
+
+        let node = unixfs_pb::parse_pb_node(&data).map_err(|e| ResolveError::Decoding(cid, e))?;
+        let fsdata =
+            unixfs_pb::parse_unixfs_data(&node.data).map_err(|e| ResolveError::Decoding(cid, e))?;
+
+        current = match fsdata.kind {
+            Some(UnixFsType::Directory) => node
+                .links
+                .into_iter()
+                .find(|link| link.name == *segment)
+                .map(|link| link.cid)
+                .ok_or_else(|| ResolveError::NotFound(cid, segment.to_string()))?,
+            Some(UnixFsType::HamtShard) => {
+                resolve_in_shard(ipfs, cid, node, &fsdata, segment).await?
+            }
+            Some(other) => return Err(ResolveError::NotADirectory(cid, other)),
+            None => return Err(ResolveError::MissingUnixFsType(cid)),
+        };
+    }
+
+    Ok(current)
+}
+
+/// Looks up `name` inside the HAMT shard rooted at `node` (the already
+/// loaded and decoded block for `cid`), descending into child shards as
+/// needed.
+async fn resolve_in_shard<Types: IpfsTypes>(
+    ipfs: &Ipfs<Types>,
+    mut cid: Cid,
+    mut node: PbNode,
+    fsdata: &UnixFsData,
+    name: &str,
+) -> Result<Cid, ResolveError> {
+    use bitswap::Block;
+
+    let fanout = fsdata.fanout.unwrap_or(256);
+    let bits = fanout.trailing_zeros().max(1);
+    let prefix_len = shard_prefix_len(fanout);
+    let hash = hamt::name_hash(name);
+    let mut level = 0u32;
+
+    // `bucket_index` consumes `bits` bits of the 64-bit hash per level, so
+    // beyond this many levels the hash is exhausted and every further level
+    // would deterministically bucket into 0 -- descending further can only
+    // be a malformed or hostile shard whose bucket-0 child link cycles back
+    // into the chain, so bail out rather than looping forever.
+    let max_levels = 64 / bits + 1;
+
+    loop {
+        if level > max_levels {
+            return Err(ResolveError::ShardTooDeep(cid, name.to_string()));
+        }
+
+        let bucket = hamt::bucket_index(hash, bits, level);
+        let prefix = format!("{:0width$X}", bucket, width = prefix_len);
+
+        let mut child_shard = None;
+        for link in &node.links {
+            if link.name == prefix {
+                child_shard = Some(link.cid);
+            } else if let Some(rest) = link.name.strip_prefix(prefix.as_str()) {
+                if rest == name {
+                    return Ok(link.cid);
+                }
+            }
+        }
+
+        let next = child_shard.ok_or_else(|| ResolveError::NotFound(cid, name.to_string()))?;
+
+        let Block { cid: next_cid, data } = ipfs
+            .get_block(&next)
+            .await
+            .map_err(|e| ResolveError::Loading(next, e))?;
+
+        node =
+            unixfs_pb::parse_pb_node(&data).map_err(|e| ResolveError::Decoding(next_cid, e))?;
+        cid = next_cid;
+        level += 1;
+    }
+}
+
+/// Types of failures which can occur while resolving an IPFS path.
+#[derive(Debug)]
+pub enum ResolveError {
+    /// Failure to load the block.
+    Loading(Cid, Error),
+    /// The block did not decode as a dag-pb node or UnixFS `Data` message.
+    Decoding(Cid, PbDecodeError),
+    /// The node is a UnixFS node, but not a directory or HAMT shard, so the
+    /// path cannot continue through it.
+    NotADirectory(Cid, UnixFsType),
+    /// The node had no UnixFS `Data` message at all.
+    MissingUnixFsType(Cid),
+    /// No entry with the given name exists in the directory at the given Cid.
+    NotFound(Cid, String),
+    /// A HAMT shard descended deeper than the name hash has bits for. Only a
+    /// malformed or cyclic shard can trigger this, since a well-formed shard
+    /// never needs more levels than the hash can address.
+    ShardTooDeep(Cid, String),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ResolveError::*;
+        match self {
+            Loading(cid, e) => write!(fmt, "loading of {} failed: {}", cid, e),
+            Decoding(cid, e) => write!(fmt, "failed to decode {}: {}", cid, e),
+            NotADirectory(cid, kind) => write!(fmt, "{} is a {}, not a directory", cid, kind),
+            MissingUnixFsType(cid) => write!(fmt, "{} has no UnixFS Data message", cid),
+            NotFound(cid, name) => write!(fmt, "no entry named {:?} in {}", name, cid),
+            ShardTooDeep(cid, name) => write!(
+                fmt,
+                "HAMT shard at {} descended too deep looking for {:?}; the shard is malformed or cyclic",
+                cid, name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use ResolveError::*;
+
+        match self {
+            Loading(_, _) => {
+                // FIXME: anyhow::Error cannot be given out as source.
+                None
+            }
+            Decoding(_, e) => Some(e),
+            NotADirectory(_, _) | MissingUnixFsType(_) | NotFound(_, _) | ShardTooDeep(_, _) => None,
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! Minimal protobuf encoding and decoding for dag-pb nodes and the UnixFS
+//! `Data` message embedded within their `Data` field. Only the fixed set of
+//! fields the directory/HAMT/MFS code cares about is handled; there is no
+//! need to pull in a general-purpose protobuf dependency for this.
+
+use libipld::cid::Cid;
+use libipld::multihash::{Code, MultihashDigest};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// dag-pb multicodec code.
+pub(crate) const DAG_PB: u64 = 0x70;
+
+/// raw multicodec code, used by leaves created with `--raw-leaves` (the
+/// default): the block's bytes are the file content directly, with no
+/// dag-pb or UnixFS `Data` wrapping.
+pub(crate) const RAW: u64 = 0x55;
+
+/// A single link out of a dag-pb node.
+#[derive(Debug, Clone)]
+pub(crate) struct PbLink {
+    pub(crate) cid: Cid,
+    pub(crate) name: String,
+    pub(crate) tsize: u64,
+}
+
+/// A decoded dag-pb node: the embedded UnixFS bytes (if any) and the
+/// outgoing links, in on-disk order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PbNode {
+    pub(crate) data: Vec<u8>,
+    pub(crate) links: Vec<PbLink>,
+}
+
+/// The UnixFS node kind, from `Data.Type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UnixFsType {
+    Raw,
+    Directory,
+    File,
+    Metadata,
+    Symlink,
+    HamtShard,
+}
+
+impl TryFrom<u64> for UnixFsType {
+    type Error = PbDecodeError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => UnixFsType::Raw,
+            1 => UnixFsType::Directory,
+            2 => UnixFsType::File,
+            3 => UnixFsType::Metadata,
+            4 => UnixFsType::Symlink,
+            5 => UnixFsType::HamtShard,
+            other => return Err(PbDecodeError::UnknownUnixFsType(other)),
+        })
+    }
+}
+
+impl fmt::Display for UnixFsType {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UnixFsType::*;
+        match self {
+            Raw => write!(fmt, "raw"),
+            Directory => write!(fmt, "directory"),
+            File => write!(fmt, "file"),
+            Metadata => write!(fmt, "metadata"),
+            Symlink => write!(fmt, "symlink"),
+            HamtShard => write!(fmt, "hamt shard"),
+        }
+    }
+}
+
+/// The decoded UnixFS `Data` message.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UnixFsData {
+    pub(crate) kind: Option<UnixFsType>,
+    /// Raw bytes of the `Data` field; for a `HamtShard` node this is the bitfield.
+    pub(crate) data: Vec<u8>,
+    pub(crate) filesize: Option<u64>,
+    pub(crate) fanout: Option<u64>,
+    pub(crate) mode: Option<u32>,
+    /// `(seconds, fractional_nanoseconds)` since the Unix epoch.
+    pub(crate) mtime: Option<(i64, u32)>,
+}
+
+/// Failures while decoding a dag-pb node or its embedded UnixFS `Data`.
+#[derive(Debug)]
+pub(crate) enum PbDecodeError {
+    Truncated,
+    UnsupportedWireType(u64),
+    InvalidCid(libipld::cid::Error),
+    InvalidUtf8(std::str::Utf8Error),
+    UnknownUnixFsType(u64),
+}
+
+impl fmt::Display for PbDecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PbDecodeError::*;
+        match self {
+            Truncated => write!(fmt, "protobuf message ended unexpectedly"),
+            UnsupportedWireType(n) => write!(fmt, "unsupported protobuf wire type {}", n),
+            InvalidCid(e) => write!(fmt, "link hash is not a valid Cid: {}", e),
+            InvalidUtf8(e) => write!(fmt, "link name is not valid utf-8: {}", e),
+            UnknownUnixFsType(n) => write!(fmt, "unknown UnixFS Data.Type {}", n),
+        }
+    }
+}
+
+impl std::error::Error for PbDecodeError {}
+
+enum WireValue<'a> {
+    Varint(u64),
+    LengthDelimited(&'a [u8]),
+}
+
+/// Walks a protobuf-encoded message, yielding `(field_number, value)` pairs.
+struct FieldReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        FieldReader { buf, pos: 0 }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, PbDecodeError> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *self.buf.get(self.pos).ok_or(PbDecodeError::Truncated)?;
+            self.pos += 1;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        Ok(result)
+    }
+}
+
+impl<'a> Iterator for FieldReader<'a> {
+    type Item = Result<(u64, WireValue<'a>), PbDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let tag = match self.read_varint() {
+            Ok(tag) => tag,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let field = tag >> 3;
+        match tag & 0x7 {
+            0 => Some(self.read_varint().map(|v| (field, WireValue::Varint(v)))),
+            2 => {
+                let len = match self.read_varint() {
+                    Ok(len) => len as usize,
+                    Err(e) => return Some(Err(e)),
+                };
+                let end = self.pos + len;
+                if end > self.buf.len() {
+                    return Some(Err(PbDecodeError::Truncated));
+                }
+                let slice = &self.buf[self.pos..end];
+                self.pos = end;
+                Some(Ok((field, WireValue::LengthDelimited(slice))))
+            }
+            other => Some(Err(PbDecodeError::UnsupportedWireType(other))),
+        }
+    }
+}
+
+pub(crate) fn parse_pb_node(bytes: &[u8]) -> Result<PbNode, PbDecodeError> {
+    let mut node = PbNode::default();
+
+    for field in FieldReader::new(bytes) {
+        match field? {
+            (1, WireValue::LengthDelimited(data)) => node.data = data.to_vec(),
+            (2, WireValue::LengthDelimited(link)) => node.links.push(parse_pb_link(link)?),
+            _ => {}
+        }
+    }
+
+    Ok(node)
+}
+
+fn parse_pb_link(bytes: &[u8]) -> Result<PbLink, PbDecodeError> {
+    let mut hash: Option<&[u8]> = None;
+    let mut name = String::new();
+    let mut tsize = 0u64;
+
+    for field in FieldReader::new(bytes) {
+        match field? {
+            (1, WireValue::LengthDelimited(bytes)) => hash = Some(bytes),
+            (2, WireValue::LengthDelimited(bytes)) => {
+                name = std::str::from_utf8(bytes)
+                    .map_err(PbDecodeError::InvalidUtf8)?
+                    .to_string();
+            }
+            (3, WireValue::Varint(v)) => tsize = v,
+            _ => {}
+        }
+    }
+
+    let hash = hash.ok_or(PbDecodeError::Truncated)?;
+    let cid = Cid::try_from(hash).map_err(PbDecodeError::InvalidCid)?;
+
+    Ok(PbLink { cid, name, tsize })
+}
+
+pub(crate) fn parse_unixfs_data(bytes: &[u8]) -> Result<UnixFsData, PbDecodeError> {
+    let mut out = UnixFsData::default();
+
+    for field in FieldReader::new(bytes) {
+        match field? {
+            (1, WireValue::Varint(v)) => out.kind = Some(UnixFsType::try_from(v)?),
+            (2, WireValue::LengthDelimited(data)) => out.data = data.to_vec(),
+            (3, WireValue::Varint(v)) => out.filesize = Some(v),
+            (6, WireValue::Varint(v)) => out.fanout = Some(v),
+            (7, WireValue::Varint(v)) => out.mode = Some(v as u32),
+            (8, WireValue::LengthDelimited(bytes)) => out.mtime = Some(parse_mtime(bytes)?),
+            _ => {}
+        }
+    }
+
+    Ok(out)
+}
+
+/// Decodes a UnixFS `UnixTime` submessage: `Seconds` (field 1, varint,
+/// interpreted as a signed zig-zag-free int64) and `FractionalNanoseconds`
+/// (field 2, varint).
+fn parse_mtime(bytes: &[u8]) -> Result<(i64, u32), PbDecodeError> {
+    let mut seconds = 0i64;
+    let mut nanos = 0u32;
+
+    for field in FieldReader::new(bytes) {
+        match field? {
+            (1, WireValue::Varint(v)) => seconds = v as i64,
+            (2, WireValue::Varint(v)) => nanos = v as u32,
+            _ => {}
+        }
+    }
+
+    Ok((seconds, nanos))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_tag(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    write_varint(out, (field << 3) | wire_type);
+}
+
+fn write_length_delimited(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_tag(out, field, 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+impl UnixFsType {
+    fn as_u64(self) -> u64 {
+        match self {
+            UnixFsType::Raw => 0,
+            UnixFsType::Directory => 1,
+            UnixFsType::File => 2,
+            UnixFsType::Metadata => 3,
+            UnixFsType::Symlink => 4,
+            UnixFsType::HamtShard => 5,
+        }
+    }
+}
+
+/// Encodes a UnixFS `Data` message.
+pub(crate) fn encode_unixfs_data(data: &UnixFsData) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    if let Some(kind) = data.kind {
+        write_tag(&mut out, 1, 0);
+        write_varint(&mut out, kind.as_u64());
+    }
+    if !data.data.is_empty() {
+        write_length_delimited(&mut out, 2, &data.data);
+    }
+    if let Some(filesize) = data.filesize {
+        write_tag(&mut out, 3, 0);
+        write_varint(&mut out, filesize);
+    }
+    if let Some(fanout) = data.fanout {
+        write_tag(&mut out, 6, 0);
+        write_varint(&mut out, fanout);
+    }
+    if let Some(mode) = data.mode {
+        write_tag(&mut out, 7, 0);
+        write_varint(&mut out, mode as u64);
+    }
+    if let Some((seconds, nanos)) = data.mtime {
+        write_length_delimited(&mut out, 8, &encode_mtime(seconds, nanos));
+    }
+
+    out
+}
+
+fn encode_mtime(seconds: i64, nanos: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    if seconds != 0 {
+        write_tag(&mut out, 1, 0);
+        write_varint(&mut out, seconds as u64);
+    }
+    if nanos != 0 {
+        write_tag(&mut out, 2, 0);
+        write_varint(&mut out, nanos as u64);
+    }
+    out
+}
+
+/// Encodes a single dag-pb link. `Name` and `Tsize` are both optional in the
+/// canonical form, and are omitted rather than written as empty/zero so that
+/// MFS-computed Cids interoperate with go-ipfs/js-ipfs for the same tree.
+fn encode_pb_link(link: &PbLink) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_length_delimited(&mut out, 1, &link.cid.to_bytes());
+    if !link.name.is_empty() {
+        write_length_delimited(&mut out, 2, link.name.as_bytes());
+    }
+    if link.tsize != 0 {
+        write_tag(&mut out, 3, 0);
+        write_varint(&mut out, link.tsize);
+    }
+    out
+}
+
+/// Encodes a dag-pb node. Links are written in the order given, which must
+/// already be sorted by name as dag-pb requires. Per the canonical dag-pb
+/// form, `Links` (field 2) is written before `Data` (field 1); strict
+/// decoders (go-ipfs, js-ipfs, libipld) reject nodes that don't follow this
+/// order.
+pub(crate) fn encode_pb_node(node: &PbNode) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for link in &node.links {
+        let encoded = encode_pb_link(link);
+        write_length_delimited(&mut out, 2, &encoded);
+    }
+    if !node.data.is_empty() {
+        write_length_delimited(&mut out, 1, &node.data);
+    }
+
+    out
+}
+
+/// Computes the dag-pb Cid (v1, sha2-256) for the given already-encoded
+/// dag-pb node bytes.
+pub(crate) fn cid_for_dag_pb(bytes: &[u8]) -> Cid {
+    let hash = Code::Sha2_256.digest(bytes);
+    Cid::new_v1(DAG_PB, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_cid(n: u8) -> Cid {
+        let hash = Code::Sha2_256.digest(&[n]);
+        Cid::new_v1(DAG_PB, hash)
+    }
+
+    #[test]
+    fn pb_node_round_trips() {
+        let node = PbNode {
+            data: vec![1, 2, 3],
+            links: vec![
+                PbLink { cid: sample_cid(0), name: "a".into(), tsize: 10 },
+                PbLink { cid: sample_cid(1), name: "b".into(), tsize: 20 },
+            ],
+        };
+
+        let encoded = encode_pb_node(&node);
+        let decoded = parse_pb_node(&encoded).expect("valid pb node");
+
+        assert_eq!(decoded.data, node.data);
+        assert_eq!(decoded.links.len(), 2);
+        assert_eq!(decoded.links[0].name, "a");
+        assert_eq!(decoded.links[0].cid, sample_cid(0));
+        assert_eq!(decoded.links[0].tsize, 10);
+        assert_eq!(decoded.links[1].name, "b");
+        assert_eq!(decoded.links[1].cid, sample_cid(1));
+        assert_eq!(decoded.links[1].tsize, 20);
+    }
+
+    #[test]
+    fn pb_node_encodes_links_before_data() {
+        // Canonical dag-pb: Links (field 2, tag 0x12) must precede Data
+        // (field 1, tag 0x0a) on the wire, regardless of struct field order.
+        let node = PbNode {
+            data: vec![9, 9],
+            links: vec![PbLink { cid: sample_cid(0), name: "x".into(), tsize: 1 }],
+        };
+
+        let encoded = encode_pb_node(&node);
+        let links_tag_pos = encoded.iter().position(|&b| b == 0x12).unwrap();
+        let data_tag_pos = encoded.iter().position(|&b| b == 0x0a).unwrap();
+        assert!(links_tag_pos < data_tag_pos);
+    }
+
+    #[test]
+    fn pb_link_omits_empty_name_and_zero_tsize() {
+        // Canonical dag-pb treats Name and Tsize as optional; a link with no
+        // name and no recorded size must not carry an empty-string Name field
+        // or a Tsize(0) field on the wire, or the Cid won't match go-ipfs/js-ipfs
+        // encoding the same link.
+        let link = PbLink { cid: sample_cid(0), name: String::new(), tsize: 0 };
+        let encoded = encode_pb_link(&link);
+
+        // Only Hash (field 1) may be on the wire: its tag, its length, then the
+        // Cid bytes. Scanning the whole buffer for the 0x12/0x18 tag bytes would
+        // not work, since the Cid bytes contain 0x12 (the sha2-256 multihash code).
+        let cid_bytes = link.cid.to_bytes();
+        assert_eq!(&encoded[..2], &[0x0a, cid_bytes.len() as u8][..], "Hash (field 1) must come first");
+        assert_eq!(encoded.len(), 2 + cid_bytes.len(), "Name (field 2) and Tsize (field 3) must be omitted");
+
+        let decoded = parse_pb_link(&encoded).expect("valid pb link");
+        assert_eq!(decoded.name, "");
+        assert_eq!(decoded.tsize, 0);
+    }
+
+    #[test]
+    fn unixfs_data_round_trips_mode_and_mtime() {
+        let data = UnixFsData {
+            kind: Some(UnixFsType::File),
+            data: vec![4, 5, 6],
+            filesize: Some(3),
+            fanout: None,
+            mode: Some(0o644),
+            mtime: Some((1_700_000_000, 42)),
+        };
+
+        let encoded = encode_unixfs_data(&data);
+        let decoded = parse_unixfs_data(&encoded).expect("valid UnixFS Data message");
+
+        assert_eq!(decoded.kind, data.kind);
+        assert_eq!(decoded.data, data.data);
+        assert_eq!(decoded.filesize, data.filesize);
+        assert_eq!(decoded.mode, data.mode);
+        assert_eq!(decoded.mtime, data.mtime);
+    }
+}